@@ -81,6 +81,8 @@ pub struct AgentCapabilities {
     pub push_notifications: bool,
     #[serde(rename = "stateTransition")]
     pub state_transition: bool,
+    #[serde(rename = "artifactSupport", default)]
+    pub artifact_support: bool,
 }
 
 /// AuthenticationInfo describes supported auth schemes
@@ -99,6 +101,27 @@ pub struct Skill {
     pub examples: Option<Vec<String>>,
 }
 
+impl AgentCard {
+    /// Whether this card actually advertises `cap`. The well-known A2A
+    /// capability names map onto `AgentCapabilities`' fixed fields; anything
+    /// else is checked against the agent's declared skills (by id or tag)
+    /// since `skills` is where agent-specific capabilities are listed.
+    pub fn declares_capability(&self, cap: &str) -> bool {
+        match cap {
+            "a2a" => true,
+            "streaming" => self.capabilities.streaming,
+            "pushNotifications" => self.capabilities.push_notifications,
+            "stateManagement" => self.capabilities.state_transition,
+            "artifactSupport" => self.capabilities.artifact_support,
+            other => self.skills.as_ref().is_some_and(|skills| {
+                skills
+                    .iter()
+                    .any(|skill| skill.id == other || skill.tags.as_ref().is_some_and(|tags| tags.iter().any(|t| t == other)))
+            }),
+        }
+    }
+}
+
 impl A2ARequest {
     /// Validate the request has required fields
     pub fn validate(&self) -> Result<(), ValidationError> {
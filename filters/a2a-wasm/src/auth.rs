@@ -0,0 +1,212 @@
+// Identity Verification and Violation Tracking
+// Resolves a caller's identity from request headers (SPIFFE ID or a
+// JWKS-verified bearer JWT) and encodes/decodes the violation counters
+// lib.rs's trust-score decay is keyed on.
+
+use serde::{Deserialize, Serialize};
+
+/// Trust score assigned to an identity with no recorded violations.
+pub const INITIAL_TRUST_SCORE: f64 = 1.0;
+
+/// Shared-data key an identity's violation counter is stored under.
+pub fn violation_key(identity: &str) -> String {
+    format!("a2a:trust:violations:{}", identity)
+}
+
+/// Decodes a violation counter from shared data, defaulting to zero for an
+/// identity that hasn't violated anything yet.
+pub fn decode_violations(bytes: Option<&[u8]>) -> u32 {
+    bytes
+        .and_then(|b| <[u8; 4]>::try_from(b).ok())
+        .map(u32::from_le_bytes)
+        .unwrap_or(0)
+}
+
+/// Encodes a violation counter for shared data.
+pub fn encode_violations(count: u32) -> Vec<u8> {
+    count.to_le_bytes().to_vec()
+}
+
+/// A caller identity resolved from request headers, tagged with the scheme
+/// it was authenticated through so it can be cross-checked against an
+/// AgentCard's advertised `AuthenticationInfo.schemes`.
+#[derive(Debug, Clone)]
+pub enum Identity {
+    /// An `x-spiffe-id` header, trusted as-is since mTLS already
+    /// authenticated it upstream of this filter.
+    Spiffe(String),
+    /// The `sub` claim of a bearer JWT that verified against `jwks`.
+    Jwt { subject: String },
+    /// No `x-spiffe-id` header or bearer token was presented. Keyed on
+    /// `:authority` (falling back to `"unknown"`) so anonymous agents still
+    /// get rate-limited and trust-scored rather than rejected outright.
+    Unauthenticated(String),
+}
+
+impl Identity {
+    /// The string used to key rate-limit buckets and violation counters.
+    pub fn key(&self) -> &str {
+        match self {
+            Identity::Spiffe(id) => id,
+            Identity::Jwt { subject } => subject,
+            Identity::Unauthenticated(authority) => authority,
+        }
+    }
+
+    /// The `AuthenticationInfo.schemes` name this identity corresponds to.
+    pub fn scheme(&self) -> &'static str {
+        match self {
+            Identity::Spiffe(_) => "mtls",
+            Identity::Jwt { .. } => "bearer",
+            Identity::Unauthenticated(_) => "none",
+        }
+    }
+}
+
+/// A JSON Web Key Set, as configured by the operator for JWT verification.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Jwks {
+    pub keys: Vec<Jwk>,
+}
+
+/// A single key from a JWKS document, per RFC 7517. Only the RSA and EC
+/// fields used by RS256/ES256 verification are modeled.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Jwk {
+    pub kid: String,
+    pub kty: String,
+    pub alg: String,
+    #[serde(default)]
+    pub n: Option<String>,
+    #[serde(default)]
+    pub e: Option<String>,
+    #[serde(default)]
+    pub x: Option<String>,
+    #[serde(default)]
+    pub y: Option<String>,
+}
+
+impl Jwk {
+    fn decoding_key(&self) -> Result<jsonwebtoken::DecodingKey, String> {
+        match self.kty.as_str() {
+            "RSA" => {
+                let n = self.n.as_deref().ok_or("RSA JWK is missing n")?;
+                let e = self.e.as_deref().ok_or("RSA JWK is missing e")?;
+                jsonwebtoken::DecodingKey::from_rsa_components(n, e).map_err(|e| e.to_string())
+            }
+            "EC" => {
+                let x = self.x.as_deref().ok_or("EC JWK is missing x")?;
+                let y = self.y.as_deref().ok_or("EC JWK is missing y")?;
+                jsonwebtoken::DecodingKey::from_ec_components(x, y).map_err(|e| e.to_string())
+            }
+            other => Err(format!("unsupported JWK key type: {}", other)),
+        }
+    }
+
+    fn algorithm(&self) -> Result<jsonwebtoken::Algorithm, String> {
+        match self.alg.as_str() {
+            "RS256" => Ok(jsonwebtoken::Algorithm::RS256),
+            "ES256" => Ok(jsonwebtoken::Algorithm::ES256),
+            other => Err(format!("unsupported JWK algorithm: {}", other)),
+        }
+    }
+}
+
+/// The claims this filter requires of a bearer JWT. `exp` is validated by
+/// `jsonwebtoken` itself once decoded against this struct.
+#[derive(Debug, Deserialize)]
+struct Claims {
+    sub: String,
+}
+
+/// Resolves the caller's identity from request headers: an `x-spiffe-id`
+/// header is trusted as-is, while an `authorization: Bearer <jwt>` header is
+/// cryptographically verified against `jwks` and its `exp`/`aud`/`iss`
+/// claims checked against the configured expectations. If neither is
+/// present, falls back to an `Identity::Unauthenticated` keyed on
+/// `authority` rather than failing, so anonymous agents are still
+/// rate-limited and trust-scored instead of rejected outright. Only a
+/// *presented but invalid* bearer token (no JWKS configured, unknown `kid`,
+/// bad signature, ...) is treated as an authentication failure.
+pub fn resolve_identity(
+    spiffe_id: Option<&str>,
+    authorization: Option<&str>,
+    authority: Option<&str>,
+    jwks: Option<&Jwks>,
+    expected_audience: Option<&str>,
+    expected_issuer: Option<&str>,
+) -> Result<Identity, String> {
+    if let Some(id) = spiffe_id {
+        return Ok(Identity::Spiffe(id.to_string()));
+    }
+
+    let Some(token) = authorization.and_then(|h| h.strip_prefix("Bearer ")) else {
+        return Ok(Identity::Unauthenticated(authority.unwrap_or("unknown").to_string()));
+    };
+
+    let jwks = jwks.ok_or("no JWKS configured to verify bearer tokens")?;
+
+    let header = jsonwebtoken::decode_header(token).map_err(|e| e.to_string())?;
+    let kid = header.kid.ok_or("JWT is missing a kid header")?;
+    let jwk = jwks
+        .keys
+        .iter()
+        .find(|k| k.kid == kid)
+        .ok_or_else(|| format!("no JWKS key matches kid {}", kid))?;
+
+    let decoding_key = jwk.decoding_key()?;
+    let mut validation = jsonwebtoken::Validation::new(jwk.algorithm()?);
+    match expected_audience {
+        Some(aud) => validation.set_audience(&[aud]),
+        None => validation.validate_aud = false,
+    }
+    if let Some(iss) = expected_issuer {
+        validation.set_issuer(&[iss]);
+    }
+
+    let data = jsonwebtoken::decode::<Claims>(token, &decoding_key, &validation).map_err(|e| e.to_string())?;
+    Ok(Identity::Jwt { subject: data.claims.sub })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_violations_defaults_to_zero() {
+        assert_eq!(decode_violations(None), 0);
+    }
+
+    #[test]
+    fn test_violation_counter_round_trips() {
+        let encoded = encode_violations(7);
+        assert_eq!(decode_violations(Some(&encoded)), 7);
+    }
+
+    #[test]
+    fn test_resolve_identity_prefers_spiffe_id() {
+        let identity =
+            resolve_identity(Some("spiffe://cluster/agent"), None, Some("agent.example.com"), None, None, None).unwrap();
+        assert_eq!(identity.key(), "spiffe://cluster/agent");
+        assert_eq!(identity.scheme(), "mtls");
+    }
+
+    #[test]
+    fn test_resolve_identity_falls_back_to_authority() {
+        let identity = resolve_identity(None, None, Some("agent.example.com"), None, None, None).unwrap();
+        assert_eq!(identity.key(), "agent.example.com");
+        assert_eq!(identity.scheme(), "none");
+    }
+
+    #[test]
+    fn test_resolve_identity_falls_back_to_unknown() {
+        let identity = resolve_identity(None, None, None, None, None, None).unwrap();
+        assert_eq!(identity.key(), "unknown");
+    }
+
+    #[test]
+    fn test_resolve_identity_requires_jwks_for_bearer_tokens() {
+        let err = resolve_identity(None, Some("Bearer abc.def.ghi"), None, None, None, None).unwrap_err();
+        assert!(err.contains("no JWKS configured"));
+    }
+}
@@ -0,0 +1,157 @@
+// AgentCard Discovery
+// Fetches and caches the well-known AgentCard document for an upstream
+// agent so capability claims in incoming requests can be checked against
+// what the agent actually advertises, instead of a hardcoded whitelist.
+
+use crate::a2a::AgentCard;
+use serde::{Deserialize, Serialize};
+
+/// Path an agent's AgentCard is expected to be served from, per the A2A
+/// discovery convention.
+pub const AGENT_CARD_PATH: &str = "/.well-known/agent.json";
+
+/// How long a cached AgentCard is trusted before it's re-fetched.
+pub const AGENT_CARD_TTL_SECONDS: f64 = 300.0;
+
+/// Envoy cluster AgentCard discovery requests are dispatched to. Operators
+/// must configure a cluster with this name able to reach upstream agents'
+/// `:authority` hosts. A future config field will make this selectable
+/// instead of a fixed name.
+pub const AGENT_CARD_CLUSTER: &str = "agent_card_discovery";
+
+/// An AgentCard cached alongside the time it was fetched, so staleness can
+/// be checked without another round trip.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CachedAgentCard {
+    pub card: AgentCard,
+    pub fetched_at: f64,
+}
+
+/// Shared-data key an authority's cached AgentCard is stored under.
+pub fn agent_card_key(authority: &str) -> String {
+    format!("a2a:agent_card:{}", authority)
+}
+
+/// Decodes a cached AgentCard from shared data, if present and parseable.
+pub fn decode_cached(bytes: Option<&[u8]>) -> Option<CachedAgentCard> {
+    bytes.and_then(|b| serde_json::from_slice(b).ok())
+}
+
+/// Whether a cached AgentCard is still within its TTL.
+pub fn is_fresh(cached: &CachedAgentCard, now: f64) -> bool {
+    now - cached.fetched_at < AGENT_CARD_TTL_SECONDS
+}
+
+/// Parses an AgentCard out of a `dispatch_http_call` response body.
+pub fn parse_agent_card(body: &[u8]) -> Result<AgentCard, String> {
+    serde_json::from_slice(body).map_err(|e| format!("invalid AgentCard JSON: {}", e))
+}
+
+/// Returns the first requested capability the card doesn't advertise, if
+/// any, so the filter can reject requests claiming unsupported behavior.
+pub fn unsupported_capability<'a>(card: &AgentCard, capabilities: &'a [String]) -> Option<&'a str> {
+    capabilities
+        .iter()
+        .find(|cap| !card.declares_capability(cap))
+        .map(|cap| cap.as_str())
+}
+
+/// Whether `scheme` isn't among the `AuthenticationInfo.schemes` the card
+/// advertises. A card with no `authentication` section doesn't restrict
+/// which schemes the gateway accepts.
+pub fn unsupported_auth_scheme(card: &AgentCard, scheme: &str) -> bool {
+    card.authentication
+        .as_ref()
+        .is_some_and(|auth| !auth.schemes.iter().any(|s| s == scheme))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::a2a::{AgentCapabilities, AuthenticationInfo};
+
+    fn sample_card() -> AgentCard {
+        AgentCard {
+            name: "test-agent".to_string(),
+            description: "a test agent".to_string(),
+            url: "https://agent.example.com".to_string(),
+            version: "1.0".to_string(),
+            capabilities: AgentCapabilities {
+                streaming: true,
+                push_notifications: false,
+                state_transition: false,
+                artifact_support: false,
+            },
+            authentication: Some(AuthenticationInfo {
+                schemes: vec!["bearer".to_string()],
+            }),
+            skills: None,
+        }
+    }
+
+    #[test]
+    fn test_is_fresh_within_ttl() {
+        let cached = CachedAgentCard {
+            card: sample_card(),
+            fetched_at: 100.0,
+        };
+        assert!(is_fresh(&cached, 100.0 + AGENT_CARD_TTL_SECONDS - 1.0));
+    }
+
+    #[test]
+    fn test_is_fresh_expired() {
+        let cached = CachedAgentCard {
+            card: sample_card(),
+            fetched_at: 0.0,
+        };
+        assert!(!is_fresh(&cached, AGENT_CARD_TTL_SECONDS + 1.0));
+    }
+
+    #[test]
+    fn test_unsupported_capability_flags_unadvertised() {
+        let card = sample_card();
+        let caps = vec!["a2a".to_string(), "pushNotifications".to_string()];
+        assert_eq!(unsupported_capability(&card, &caps), Some("pushNotifications"));
+    }
+
+    #[test]
+    fn test_unsupported_capability_allows_advertised() {
+        let card = sample_card();
+        let caps = vec!["a2a".to_string(), "streaming".to_string()];
+        assert_eq!(unsupported_capability(&card, &caps), None);
+    }
+
+    #[test]
+    fn test_unsupported_capability_flags_artifact_support_when_unset() {
+        let card = sample_card();
+        let caps = vec!["artifactSupport".to_string()];
+        assert_eq!(unsupported_capability(&card, &caps), Some("artifactSupport"));
+    }
+
+    #[test]
+    fn test_unsupported_capability_allows_artifact_support_when_set() {
+        let mut card = sample_card();
+        card.capabilities.artifact_support = true;
+        let caps = vec!["artifactSupport".to_string()];
+        assert_eq!(unsupported_capability(&card, &caps), None);
+    }
+
+    #[test]
+    fn test_unsupported_auth_scheme_flags_unadvertised() {
+        let card = sample_card();
+        assert!(unsupported_auth_scheme(&card, "mtls"));
+    }
+
+    #[test]
+    fn test_unsupported_auth_scheme_allows_advertised() {
+        let card = sample_card();
+        assert!(!unsupported_auth_scheme(&card, "bearer"));
+    }
+
+    #[test]
+    fn test_unsupported_auth_scheme_allows_any_without_authentication_section() {
+        let mut card = sample_card();
+        card.authentication = None;
+        assert!(!unsupported_auth_scheme(&card, "mtls"));
+    }
+}
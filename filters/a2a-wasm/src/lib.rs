@@ -3,22 +3,97 @@
 
 use proxy_wasm::traits::*;
 use proxy_wasm::types::*;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::time::{Duration, UNIX_EPOCH};
 
 mod a2a;
+mod auth;
+mod discovery;
+mod rate_limit;
+mod sse;
 mod validation;
 
-use a2a::A2ARequest;
-use validation::{calculate_trust_score, check_token_bucket, estimate_tokens, validate_a2a_payload};
+use a2a::{A2ARequest, AgentCard};
+use auth::Identity;
+use rate_limit::RateLimitDecision;
+use serde::Deserialize;
+use validation::{
+    calculate_trust_score, check_token_bucket, default_allowed_capabilities, estimate_parts_tokens,
+    estimate_tokens, validate_a2a_payload, BpeVocab, ValidationResult,
+};
+
+/// Seconds since the Unix epoch, per this context's view of the current
+/// time. Shared by `A2AFilter` and `A2AFilterRoot` so bucket/cache
+/// timestamps are computed consistently.
+fn current_unix_seconds<C: Context + ?Sized>(ctx: &C) -> f64 {
+    ctx.get_current_time()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs_f64()
+}
+
+/// Shared queue used to nudge every worker's root context into pruning its
+/// stale rate-limit buckets, not just the one whose tick happened to fire.
+const BUCKET_PRUNE_QUEUE: &str = "a2a_bucket_prune";
+
+/// How often this worker checks its own buckets for staleness.
+const BUCKET_PRUNE_INTERVAL: Duration = Duration::from_secs(60);
 
 #[no_mangle]
 pub fn _start() {
     proxy_wasm::set_log_level(LogLevel::Info);
     proxy_wasm::set_root_context(|_| -> Box<dyn RootContext> {
-        Box::new(A2AFilterRoot)
+        Box::new(A2AFilterRoot {
+            prune_queue_id: None,
+            bpe_vocab: RefCell::new(None),
+            jwks: RefCell::new(None),
+            config: RefCell::new(FilterConfig::new()),
+        })
     });
 }
 
-struct A2AFilterRoot;
+struct A2AFilterRoot {
+    prune_queue_id: Option<u32>,
+    /// Shared across every `A2AFilter` this root spawns, so the (potentially
+    /// large) merge-rank table is parsed once per `on_configure`, not once
+    /// per request.
+    bpe_vocab: RefCell<Option<Rc<BpeVocab>>>,
+    /// The JWKS used to verify bearer JWTs, parsed once in `on_configure` and
+    /// shared the same way as `bpe_vocab`.
+    jwks: RefCell<Option<Rc<auth::Jwks>>>,
+    /// Parsed once in `on_configure` and cloned into each `A2AFilter`, since
+    /// `create_http_context` only gets `&self`.
+    config: RefCell<FilterConfig>,
+}
+
+/// Plugin configuration as received from Envoy, with every field optional
+/// so operators only need to specify what they want to change from
+/// `FilterConfig::new()`'s defaults.
+#[derive(Debug, Deserialize)]
+struct RawFilterConfig {
+    max_tokens_per_request: Option<u64>,
+    /// Ceiling on cumulative tokens a single streaming response may emit
+    /// before the gateway cuts it off; omit to use `FilterConfig::new()`'s default.
+    max_response_tokens_per_stream: Option<u64>,
+    trust_decay_constant: Option<f64>,
+    min_trust_score: Option<f64>,
+    token_bucket_capacity: Option<f64>,
+    token_bucket_replenish_rate: Option<f64>,
+    allowed_capabilities: Option<Vec<String>>,
+    /// GPT-2-style `merges.txt` blob for the BPE tokenizer; omit to keep
+    /// using the char/4 heuristic.
+    bpe_merges: Option<String>,
+    /// JWKS document (JSON, as published at a `jwks_uri`) used to verify
+    /// `authorization: Bearer` tokens; omit to reject every bearer token
+    /// (requests with `x-spiffe-id` or no credentials are still accepted).
+    jwks: Option<String>,
+    /// Expected `aud` claim on bearer JWTs; omit to skip audience validation.
+    expected_audience: Option<String>,
+    /// Expected `iss` claim on bearer JWTs; omit to skip issuer validation.
+    expected_issuer: Option<String>,
+}
 
 impl Context for A2AFilterRoot {}
 
@@ -27,27 +102,189 @@ impl RootContext for A2AFilterRoot {
         Some(ContextType::HttpContext)
     }
 
+    fn on_vm_start(&mut self, _plugin_vm_configuration_size: usize) -> bool {
+        self.prune_queue_id = Some(self.register_shared_queue(BUCKET_PRUNE_QUEUE));
+        self.set_tick_period(BUCKET_PRUNE_INTERVAL);
+        true
+    }
+
+    fn on_configure(&mut self, _plugin_configuration_size: usize) -> bool {
+        let bytes = self.get_plugin_configuration().unwrap_or_default();
+        if bytes.is_empty() {
+            // No config supplied: keep whatever defaults/previous config we have.
+            return true;
+        }
+
+        let raw: RawFilterConfig = match serde_json::from_slice(&bytes) {
+            Ok(raw) => raw,
+            Err(e) => {
+                log::error!("Invalid filter configuration: {}", e);
+                return false;
+            }
+        };
+
+        let defaults = FilterConfig::new();
+        let config = FilterConfig {
+            max_tokens_per_request: raw.max_tokens_per_request.unwrap_or(defaults.max_tokens_per_request),
+            max_response_tokens_per_stream: raw
+                .max_response_tokens_per_stream
+                .unwrap_or(defaults.max_response_tokens_per_stream),
+            trust_decay_constant: raw.trust_decay_constant.unwrap_or(defaults.trust_decay_constant),
+            min_trust_score: raw.min_trust_score.unwrap_or(defaults.min_trust_score),
+            token_bucket_capacity: raw.token_bucket_capacity.unwrap_or(defaults.token_bucket_capacity),
+            token_bucket_replenish_rate: raw
+                .token_bucket_replenish_rate
+                .unwrap_or(defaults.token_bucket_replenish_rate),
+            allowed_capabilities: raw.allowed_capabilities.unwrap_or(defaults.allowed_capabilities),
+            expected_audience: raw.expected_audience.or(defaults.expected_audience),
+            expected_issuer: raw.expected_issuer.or(defaults.expected_issuer),
+        };
+
+        if !(0.0..=1.0).contains(&config.min_trust_score) {
+            log::error!("min_trust_score must be between 0.0 and 1.0, got {}", config.min_trust_score);
+            return false;
+        }
+        if config.token_bucket_capacity <= 0.0 {
+            log::error!("token_bucket_capacity must be positive, got {}", config.token_bucket_capacity);
+            return false;
+        }
+        if config.token_bucket_replenish_rate < 0.0 {
+            log::error!(
+                "token_bucket_replenish_rate must not be negative, got {}",
+                config.token_bucket_replenish_rate
+            );
+            return false;
+        }
+
+        *self.config.borrow_mut() = config;
+
+        if let Some(merges) = raw.bpe_merges.filter(|m| !m.trim().is_empty()) {
+            let vocab = BpeVocab::from_merges(&merges);
+            if vocab.is_empty() {
+                // Falls back to the char/4 heuristic at estimation time
+                // (validation::validate_a2a_payload treats an empty vocab
+                // the same as no vocab), but still worth a loud log since
+                // it almost certainly means `bpe_merges` was malformed.
+                log::warn!("Parsed bpe_merges into an empty vocabulary; falling back to the char/4 heuristic");
+            } else {
+                log::info!("Loaded BPE vocabulary with {} merge rule(s)", vocab.len());
+            }
+            *self.bpe_vocab.borrow_mut() = Some(Rc::new(vocab));
+        } else {
+            *self.bpe_vocab.borrow_mut() = None;
+        }
+
+        if let Some(jwks_json) = raw.jwks.filter(|j| !j.trim().is_empty()) {
+            match serde_json::from_str::<auth::Jwks>(&jwks_json) {
+                Ok(jwks) => {
+                    log::info!("Loaded JWKS with {} key(s)", jwks.keys.len());
+                    *self.jwks.borrow_mut() = Some(Rc::new(jwks));
+                }
+                Err(e) => {
+                    log::error!("Invalid JWKS configuration: {}", e);
+                    return false;
+                }
+            }
+        } else {
+            *self.jwks.borrow_mut() = None;
+        }
+
+        true
+    }
+
+    fn on_tick(&mut self) {
+        self.prune_stale_buckets();
+    }
+
+    fn on_queue_ready(&mut self, _queue_id: u32) {
+        // Another worker's tick asked us to prune too, so stale buckets
+        // don't linger on workers whose own tick hasn't fired yet.
+        self.prune_stale_buckets();
+    }
+
     fn create_http_context(&self, context_id: u32) -> Option<Box<dyn HttpContext>> {
         Some(Box::new(A2AFilter {
             context_id,
-            config: FilterConfig::default(),
+            config: self.config.borrow().clone(),
+            bpe_vocab: self.bpe_vocab.borrow().clone(),
+            jwks: self.jwks.borrow().clone(),
+            pending_discovery: None,
+            identity: None,
+            is_streaming_response: false,
+            sse_parser: sse::SseParser::new(),
+            response_tokens: 0,
+            response_token_cache: HashMap::new(),
+            response_quota_exceeded: false,
         }))
     }
 }
 
-#[derive(Debug, Default)]
+impl A2AFilterRoot {
+    /// Evicts buckets that haven't been touched in `rate_limit::BUCKET_TTL_SECONDS`
+    /// so memory doesn't grow unbounded with one-off agent identities. Sweeps
+    /// every index shard independently, since identities are spread across
+    /// `rate_limit::bucket_index_key` shards rather than one combined key.
+    fn prune_stale_buckets(&self) {
+        let now = current_unix_seconds(self);
+        let mut pruned = 0usize;
+
+        for shard_key in rate_limit::bucket_index_shard_keys() {
+            let (data, cas) = self.get_shared_data(&shard_key);
+            let mut index = rate_limit::decode_index(data.as_deref());
+            let stale = rate_limit::stale_identities(&index, now);
+            if stale.is_empty() {
+                continue;
+            }
+
+            for identity in &stale {
+                index.remove(identity);
+                let _ = self.set_shared_data(&rate_limit::bucket_key(identity), None, None);
+            }
+
+            if let Ok(encoded) = serde_json::to_vec(&index) {
+                let _ = self.set_shared_data(&shard_key, Some(&encoded), cas);
+            }
+
+            pruned += stale.len();
+        }
+
+        if pruned == 0 {
+            return;
+        }
+
+        log::info!("Pruned {} stale rate-limit bucket(s)", pruned);
+
+        if let Some(queue_id) = self.prune_queue_id {
+            let _ = self.enqueue_shared_queue(queue_id, Some(b"prune"));
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone)]
 struct FilterConfig {
     max_tokens_per_request: u64,
+    max_response_tokens_per_stream: u64,
     trust_decay_constant: f64,
     min_trust_score: f64,
+    token_bucket_capacity: f64,
+    token_bucket_replenish_rate: f64,
+    allowed_capabilities: Vec<String>,
+    expected_audience: Option<String>,
+    expected_issuer: Option<String>,
 }
 
 impl FilterConfig {
     fn new() -> Self {
         FilterConfig {
             max_tokens_per_request: 100_000,
+            max_response_tokens_per_stream: 500_000,
             trust_decay_constant: 0.1,
             min_trust_score: 0.65,
+            token_bucket_capacity: 100_000.0,
+            token_bucket_replenish_rate: 1_000.0,
+            allowed_capabilities: default_allowed_capabilities(),
+            expected_audience: None,
+            expected_issuer: None,
         }
     }
 }
@@ -55,9 +292,96 @@ impl FilterConfig {
 struct A2AFilter {
     context_id: u32,
     config: FilterConfig,
+    bpe_vocab: Option<Rc<BpeVocab>>,
+    jwks: Option<Rc<auth::Jwks>>,
+    /// Validation state held across the `Action::Pause` while an AgentCard
+    /// discovery call is in flight, resumed in `on_http_call_response`.
+    pending_discovery: Option<PendingDiscovery>,
+    /// The request's resolved identity, kept around so streamed response
+    /// tokens can be debited from the same bucket as the request was.
+    identity: Option<Identity>,
+    /// Set from the response `content-type` once headers arrive; gates
+    /// whether `on_http_response_body` runs SSE accounting at all.
+    is_streaming_response: bool,
+    sse_parser: sse::SseParser,
+    /// Running total of tokens seen in this response stream so far.
+    response_tokens: u64,
+    /// Per-word BPE cache for streamed text, kept across body callbacks the
+    /// same way `BpeVocab::encode`'s caller caches for a single request.
+    response_token_cache: HashMap<String, u64>,
+    /// Set once `response_tokens` exceeds `config.max_response_tokens_per_stream`
+    /// or the token bucket denies a debit, so the rest of the stream is
+    /// suppressed instead of re-evaluating a quota that's already blown.
+    response_quota_exceeded: bool,
 }
 
-impl Context for A2AFilter {}
+/// What's needed to finish validating a request once its AgentCard arrives.
+struct PendingDiscovery {
+    authority: String,
+    result: ValidationResult,
+    auth_scheme: &'static str,
+}
+
+impl Context for A2AFilter {
+    fn on_http_call_response(&mut self, _token_id: u32, _num_headers: usize, body_size: usize, _num_trailers: usize) {
+        let Some(pending) = self.pending_discovery.take() else {
+            self.resume_http_request();
+            return;
+        };
+
+        let card = self
+            .get_http_call_response_body(0, body_size)
+            .and_then(|body| discovery::parse_agent_card(&body).ok());
+
+        match card {
+            Some(card) => {
+                self.cache_agent_card(&pending.authority, &card);
+
+                if let Some(cap) = discovery::unsupported_capability(&card, &pending.result.capabilities) {
+                    log::warn!(
+                        "Agent at {} claimed capability not in its AgentCard: {}",
+                        pending.authority,
+                        cap
+                    );
+                    if let Some(identity) = self.identity.as_ref() {
+                        self.record_violation(identity.key());
+                    }
+                    self.send_error_response(
+                        403,
+                        &format!("capability not advertised by agent card: {}", cap),
+                    );
+                    return;
+                }
+
+                if discovery::unsupported_auth_scheme(&card, pending.auth_scheme) {
+                    log::warn!(
+                        "Agent at {} does not support auth scheme: {}",
+                        pending.authority,
+                        pending.auth_scheme
+                    );
+                    if let Some(identity) = self.identity.as_ref() {
+                        self.record_violation(identity.key());
+                    }
+                    self.send_error_response(
+                        403,
+                        &format!("auth scheme not supported by agent card: {}", pending.auth_scheme),
+                    );
+                    return;
+                }
+
+                self.apply_observability_headers(&pending.result);
+            }
+            None => {
+                // Can't verify capabilities without a card; fail open rather than
+                // block every request behind a flaky discovery endpoint.
+                log::error!("Failed to fetch or parse AgentCard for {}", pending.authority);
+                self.apply_observability_headers(&pending.result);
+            }
+        }
+
+        self.resume_http_request();
+    }
+}
 
 impl HttpContext for A2AFilter {
     fn on_http_request_headers(&mut self, _num_headers: usize, _end_of_stream: bool) -> Action {
@@ -109,11 +433,42 @@ impl HttpContext for A2AFilter {
             }
         };
 
+        // Resolve the caller's identity before anything else: it's what
+        // violations, rate limiting and the trust score are keyed on. An
+        // agent presenting no credentials at all falls back to an
+        // authority-keyed anonymous identity rather than failing here; only
+        // a presented-but-invalid bearer token is rejected.
+        let identity = match auth::resolve_identity(
+            self.get_http_request_header("x-spiffe-id").as_deref(),
+            self.get_http_request_header("authorization").as_deref(),
+            self.get_http_request_header(":authority").as_deref(),
+            self.jwks.as_deref(),
+            self.config.expected_audience.as_deref(),
+            self.config.expected_issuer.as_deref(),
+        ) {
+            Ok(identity) => identity,
+            Err(e) => {
+                log::warn!("Authentication failed: {}", e);
+                self.send_error_response(403, "authentication failed");
+                return Action::Pause;
+            }
+        };
+        self.identity = Some(identity.clone());
+
+        if let Some(action) = self.enforce_trust_score(&identity) {
+            return action;
+        }
+
         // Validate A2A payload
-        match validate_a2a_payload(body_str) {
+        match validate_a2a_payload(
+            body_str,
+            self.bpe_vocab.as_deref(),
+            &self.config.allowed_capabilities,
+        ) {
             Ok(result) => {
                 if !result.valid {
                     log::error!("A2A validation failed: {:?}", result.errors);
+                    self.record_violation(identity.key());
                     let error_json = format!(
                         r#"{{"error": "invalid_a2a_payload", "details": {:?}}"#,
                         result.errors
@@ -129,45 +484,319 @@ impl HttpContext for A2AFilter {
                         result.estimated_tokens,
                         self.config.max_tokens_per_request
                     );
+                    self.record_violation(identity.key());
                     self.send_error_response(429, "Token limit exceeded");
                     return Action::Pause;
                 }
 
-                // Add observability headers
-                self.set_http_request_header("x-a2a-validated", Some("true"));
-                self.set_http_request_header(
-                    "x-estimated-tokens",
-                    Some(&result.estimated_tokens.to_string()),
-                );
+                // Check the distributed token bucket for this agent identity
+                match self.check_token_bucket(identity.key(), result.estimated_tokens as f64) {
+                    RateLimitDecision::Allowed { remaining } => {
+                        self.set_http_request_header(
+                            "x-a2a-tokens-remaining",
+                            Some(&(remaining as u64).to_string()),
+                        );
+                    }
+                    RateLimitDecision::Denied { retry_after_seconds } => {
+                        log::warn!(
+                            "Rate limit exceeded for {}: retry after {}s",
+                            identity.key(),
+                            retry_after_seconds
+                        );
+                        self.record_violation(identity.key());
+                        self.send_http_response(
+                            429,
+                            vec![
+                                ("content-type", "application/json"),
+                                ("x-a2a-error", "true"),
+                                ("retry-after", &retry_after_seconds.to_string()),
+                            ],
+                            Some(
+                                format!(
+                                    r#"{{"error": "rate_limit_exceeded", "retry_after_seconds": {}}}"#,
+                                    retry_after_seconds
+                                )
+                                .as_bytes(),
+                            ),
+                        );
+                        return Action::Pause;
+                    }
+                }
 
-                log::info!("A2A payload validated: {} tokens", result.estimated_tokens);
+                let authority = self.get_http_request_header(":authority").unwrap_or_default();
+                self.check_capabilities(authority, result, identity.scheme())
             }
             Err(e) => {
                 log::error!("A2A validation error: {}", e);
+                self.record_violation(identity.key());
                 self.send_error_response(400, &e);
-                return Action::Pause;
+                Action::Pause
             }
         }
-
-        // Check trust score from SPIFFE ID header
-        if let Some(spiffe_id) = self.get_http_request_header("x-spiffe-id") {
-            log::debug!("SPIFFE ID: {}", spiffe_id);
-            // Trust score would be checked against a shared state
-            // For now, just log it
-        }
-
-        Action::Continue
     }
 
     fn on_http_response_headers(&mut self, _num_headers: usize, _end_of_stream: bool) -> Action {
         // Add gateway headers to response
         self.set_http_response_header("x-a2a-gateway-version", Some("0.1.0"));
         self.set_http_response_header("x-served-by", Some("a2a-wasm-filter"));
+
+        self.is_streaming_response = self
+            .get_http_response_header("content-type")
+            .is_some_and(|ct| ct.starts_with("text/event-stream"));
+
+        Action::Continue
+    }
+
+    fn on_http_response_body(&mut self, body_size: usize, end_of_stream: bool) -> Action {
+        if !self.is_streaming_response {
+            return Action::Continue;
+        }
+
+        if self.response_quota_exceeded {
+            // Already cut this stream off; keep suppressing whatever the
+            // upstream agent keeps sending.
+            self.set_http_response_body(0, body_size, &[]);
+            return Action::Continue;
+        }
+
+        let Some(chunk) = self.get_http_response_body(0, body_size) else {
+            return Action::Continue;
+        };
+
+        // Re-emit each already-approved event as it's accounted for, so a
+        // cutoff partway through this chunk forwards the events the caller
+        // was already charged for instead of discarding them along with the
+        // chunk's remaining bytes.
+        let mut forwarded = Vec::new();
+
+        for event in self.sse_parser.feed(&chunk) {
+            let parts = sse::event_parts(&event);
+            let tokens = estimate_parts_tokens(&parts, self.bpe_vocab.as_deref(), &mut self.response_token_cache);
+            self.response_tokens += tokens;
+
+            let bucket_denied = self
+                .identity
+                .as_ref()
+                .is_some_and(|identity| matches!(self.check_token_bucket(identity.key(), tokens as f64), RateLimitDecision::Denied { .. }));
+
+            if bucket_denied || self.response_tokens > self.config.max_response_tokens_per_stream {
+                log::warn!(
+                    "Response token quota exceeded mid-stream: {} tokens seen, ceiling {}",
+                    self.response_tokens,
+                    self.config.max_response_tokens_per_stream
+                );
+                self.response_quota_exceeded = true;
+                forwarded.extend_from_slice(&sse::error_frame("response token quota exceeded"));
+                self.set_http_response_body(0, body_size, &forwarded);
+                self.set_http_response_trailer("x-a2a-response-tokens", Some(&self.response_tokens.to_string()));
+                return Action::Continue;
+            }
+
+            forwarded.extend_from_slice(&sse::reencode_event(&event));
+        }
+
+        if end_of_stream {
+            self.set_http_response_trailer("x-a2a-response-tokens", Some(&self.response_tokens.to_string()));
+        }
+
         Action::Continue
     }
 }
 
 impl A2AFilter {
+    /// Cross-checks `result`'s claimed capabilities against `authority`'s
+    /// AgentCard, fetching and caching it first if there's no fresh copy in
+    /// shared data. A cache miss dispatches an out-of-band call and pauses
+    /// the request; the rest of validation finishes in
+    /// `on_http_call_response` once the card arrives.
+    fn check_capabilities(&mut self, authority: String, result: ValidationResult, auth_scheme: &'static str) -> Action {
+        let now = current_unix_seconds(self);
+        let cached = discovery::decode_cached(
+            self.get_shared_data(&discovery::agent_card_key(&authority)).0.as_deref(),
+        );
+
+        if let Some(cached) = cached.filter(|c| discovery::is_fresh(c, now)) {
+            if let Some(cap) = discovery::unsupported_capability(&cached.card, &result.capabilities) {
+                log::warn!(
+                    "Agent at {} claimed capability not in its AgentCard: {}",
+                    authority,
+                    cap
+                );
+                if let Some(identity) = self.identity.as_ref() {
+                    self.record_violation(identity.key());
+                }
+                self.send_error_response(
+                    403,
+                    &format!("capability not advertised by agent card: {}", cap),
+                );
+                return Action::Pause;
+            }
+
+            if discovery::unsupported_auth_scheme(&cached.card, auth_scheme) {
+                log::warn!("Agent at {} does not support auth scheme: {}", authority, auth_scheme);
+                if let Some(identity) = self.identity.as_ref() {
+                    self.record_violation(identity.key());
+                }
+                self.send_error_response(
+                    403,
+                    &format!("auth scheme not supported by agent card: {}", auth_scheme),
+                );
+                return Action::Pause;
+            }
+
+            self.apply_observability_headers(&result);
+            return Action::Continue;
+        }
+
+        match self.dispatch_http_call(
+            discovery::AGENT_CARD_CLUSTER,
+            vec![
+                (":method", "GET"),
+                (":path", discovery::AGENT_CARD_PATH),
+                (":authority", &authority),
+            ],
+            None,
+            vec![],
+            Duration::from_secs(5),
+        ) {
+            Ok(_token_id) => {
+                self.pending_discovery = Some(PendingDiscovery { authority, result, auth_scheme });
+                Action::Pause
+            }
+            Err(e) => {
+                // Can't verify capabilities without a card; fail open rather than
+                // block every request behind a misconfigured discovery cluster.
+                log::error!("Failed to dispatch AgentCard discovery for {}: {:?}", authority, e);
+                self.apply_observability_headers(&result);
+                Action::Continue
+            }
+        }
+    }
+
+    fn cache_agent_card(&self, authority: &str, card: &AgentCard) {
+        let cached = discovery::CachedAgentCard {
+            card: card.clone(),
+            fetched_at: current_unix_seconds(self),
+        };
+        if let Ok(encoded) = serde_json::to_vec(&cached) {
+            let _ = self.set_shared_data(&discovery::agent_card_key(authority), Some(&encoded), None);
+        }
+    }
+
+    fn apply_observability_headers(&self, result: &ValidationResult) {
+        self.set_http_request_header("x-a2a-validated", Some("true"));
+        self.set_http_request_header("x-estimated-tokens", Some(&result.estimated_tokens.to_string()));
+        self.set_http_request_header(
+            "x-a2a-token-method",
+            Some(match result.estimation_method {
+                validation::TokenEstimationMethod::Heuristic => "heuristic",
+                validation::TokenEstimationMethod::Bpe => "bpe",
+            }),
+        );
+        log::info!("A2A payload validated: {} tokens", result.estimated_tokens);
+    }
+
+    /// Checks `identity`'s distributed token bucket, replenishing it based
+    /// on elapsed time and debiting `estimated_tokens`. Retries on CAS
+    /// mismatch since concurrent requests from the same agent race on the
+    /// same shared-data entry.
+    fn check_token_bucket(&self, identity: &str, estimated_tokens: f64) -> RateLimitDecision {
+        let now = current_unix_seconds(self);
+        let capacity = self.config.token_bucket_capacity;
+        let replenish_rate = self.config.token_bucket_replenish_rate;
+        let key = rate_limit::bucket_key(identity);
+
+        for _ in 0..rate_limit::MAX_CAS_RETRIES {
+            let (data, cas) = self.get_shared_data(&key);
+            let state = rate_limit::decode_bucket(data.as_deref(), capacity, now);
+            let elapsed = (now - state.last_refill).max(0.0);
+            let (allowed, remaining) =
+                check_token_bucket(state.remaining, capacity, replenish_rate, elapsed, estimated_tokens);
+
+            let new_state = rate_limit::BucketState {
+                remaining,
+                last_refill: now,
+            };
+            let encoded = serde_json::to_vec(&new_state).unwrap_or_default();
+
+            match self.set_shared_data(&key, Some(&encoded), cas) {
+                Ok(()) => {
+                    self.touch_bucket_index(identity, now);
+                    return if allowed {
+                        RateLimitDecision::Allowed { remaining }
+                    } else {
+                        RateLimitDecision::Denied {
+                            retry_after_seconds: rate_limit::retry_after_seconds(
+                                estimated_tokens - remaining,
+                                replenish_rate,
+                            ),
+                        }
+                    };
+                }
+                // Lost the CAS race to a concurrent request for the same identity; retry.
+                Err(_) => continue,
+            }
+        }
+
+        // Retries exhausted under heavy contention: fail open rather than block the agent.
+        RateLimitDecision::Allowed { remaining: 0.0 }
+    }
+
+    /// Records that `identity`'s bucket was touched at `now` so
+    /// `A2AFilterRoot::prune_stale_buckets` can find it later. Writes to
+    /// `identity`'s index shard rather than one global key, so concurrent
+    /// requests from unrelated identities don't CAS-race each other.
+    fn touch_bucket_index(&self, identity: &str, now: f64) {
+        let key = rate_limit::bucket_index_key(identity);
+        for _ in 0..rate_limit::MAX_CAS_RETRIES {
+            let (data, cas) = self.get_shared_data(&key);
+            let mut index = rate_limit::decode_index(data.as_deref());
+            index.insert(identity.to_string(), now);
+            let encoded = serde_json::to_vec(&index).unwrap_or_default();
+            if self.set_shared_data(&key, Some(&encoded), cas).is_ok() {
+                return;
+            }
+        }
+    }
+
+    /// Recomputes `identity`'s trust score from its violation count and
+    /// rejects the request if it's fallen below `config.min_trust_score`,
+    /// otherwise annotates the request with `x-a2a-trust-score`.
+    fn enforce_trust_score(&self, identity: &Identity) -> Option<Action> {
+        let violations = auth::decode_violations(
+            self.get_shared_data(&auth::violation_key(identity.key())).0.as_deref(),
+        );
+        let trust_score = calculate_trust_score(auth::INITIAL_TRUST_SCORE, self.config.trust_decay_constant, violations);
+
+        if trust_score < self.config.min_trust_score {
+            log::warn!(
+                "Trust score too low for {}: {:.3} < {:.3} ({} violation(s))",
+                identity.key(),
+                trust_score,
+                self.config.min_trust_score,
+                violations
+            );
+            self.send_error_response(403, "trust score below minimum");
+            return Some(Action::Pause);
+        }
+
+        self.set_http_request_header("x-a2a-trust-score", Some(&format!("{:.3}", trust_score)));
+        None
+    }
+
+    /// Increments `identity`'s violation counter in shared data, retrying on
+    /// CAS mismatch the same way `check_token_bucket` does.
+    fn record_violation(&self, identity: &str) {
+        let key = auth::violation_key(identity);
+        for _ in 0..rate_limit::MAX_CAS_RETRIES {
+            let (data, cas) = self.get_shared_data(&key);
+            let count = auth::decode_violations(data.as_deref()) + 1;
+            if self.set_shared_data(&key, Some(&auth::encode_violations(count)), cas).is_ok() {
+                return;
+            }
+        }
+    }
+
     fn send_error_response(&self, status: u32, message: &str) {
         let body = format!(r#"{{"error": "{}"}}"#, message);
         self.send_error_response_with_body(status, &body);
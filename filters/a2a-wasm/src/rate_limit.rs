@@ -0,0 +1,139 @@
+// Distributed Token-Bucket Rate Limiting
+// Encodes/decodes per-identity token-bucket state and retry hints for the
+// shared-data storage lib.rs drives.
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+
+/// Maximum number of compare-and-swap retries before giving up on a bucket
+/// update and failing open.
+pub const MAX_CAS_RETRIES: u32 = 5;
+
+/// How long an identity's bucket can sit untouched before it's considered
+/// stale and pruned.
+pub const BUCKET_TTL_SECONDS: f64 = 3600.0;
+
+/// Shared-data key prefix holding the set of known bucket identities and
+/// when they were last touched, since there's no host API to list
+/// shared-data keys. Split across `BUCKET_INDEX_SHARD_COUNT` keys (see
+/// `bucket_index_key`) so concurrent requests from different identities
+/// don't all CAS-race on one entry.
+pub const BUCKET_INDEX_KEY: &str = "a2a:rate_limit:index";
+
+/// Number of shards `BUCKET_INDEX_KEY` is split across.
+pub const BUCKET_INDEX_SHARD_COUNT: u32 = 16;
+
+/// Shared-data key for the index shard `identity` hashes into.
+pub fn bucket_index_key(identity: &str) -> String {
+    format!("{}:{}", BUCKET_INDEX_KEY, index_shard(identity))
+}
+
+/// Every shard key `BUCKET_INDEX_KEY` is split across, for callers (like
+/// stale-bucket pruning) that need to sweep the whole index.
+pub fn bucket_index_shard_keys() -> impl Iterator<Item = String> {
+    (0..BUCKET_INDEX_SHARD_COUNT).map(|shard| format!("{}:{}", BUCKET_INDEX_KEY, shard))
+}
+
+fn index_shard(identity: &str) -> u32 {
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    identity.hash(&mut hasher);
+    (hasher.finish() % BUCKET_INDEX_SHARD_COUNT as u64) as u32
+}
+
+/// Persisted token-bucket state for a single agent identity.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct BucketState {
+    pub remaining: f64,
+    pub last_refill: f64,
+}
+
+/// Result of checking a request against the distributed token bucket.
+#[derive(Debug, Clone, Copy)]
+pub enum RateLimitDecision {
+    Allowed { remaining: f64 },
+    Denied { retry_after_seconds: u64 },
+}
+
+/// Shared-data key for an identity's bucket state.
+pub fn bucket_key(identity: &str) -> String {
+    format!("a2a:rate_limit:bucket:{}", identity)
+}
+
+/// Decodes a bucket's shared-data payload, defaulting to a full bucket when
+/// nothing has been stored yet (first request from this identity).
+pub fn decode_bucket(bytes: Option<&[u8]>, capacity: f64, now: f64) -> BucketState {
+    bytes
+        .and_then(|b| serde_json::from_slice(b).ok())
+        .unwrap_or(BucketState {
+            remaining: capacity,
+            last_refill: now,
+        })
+}
+
+/// Computes how many whole seconds until enough tokens will have
+/// replenished to satisfy a request that was just denied.
+pub fn retry_after_seconds(deficit: f64, replenish_rate: f64) -> u64 {
+    if replenish_rate <= 0.0 {
+        return 1;
+    }
+    (deficit / replenish_rate).ceil().max(1.0) as u64
+}
+
+/// Decodes the bucket index (identity -> last-touched timestamp).
+pub fn decode_index(bytes: Option<&[u8]>) -> HashMap<String, f64> {
+    bytes.and_then(|b| serde_json::from_slice(b).ok()).unwrap_or_default()
+}
+
+/// Returns the identities that haven't been touched in `BUCKET_TTL_SECONDS`.
+pub fn stale_identities(index: &HashMap<String, f64>, now: f64) -> Vec<String> {
+    index
+        .iter()
+        .filter(|(_, &last_seen)| now - last_seen > BUCKET_TTL_SECONDS)
+        .map(|(identity, _)| identity.clone())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_bucket_defaults_to_full_capacity() {
+        let state = decode_bucket(None, 500.0, 42.0);
+        assert_eq!(state.remaining, 500.0);
+        assert_eq!(state.last_refill, 42.0);
+    }
+
+    #[test]
+    fn test_bucket_index_key_is_stable_per_identity() {
+        assert_eq!(bucket_index_key("agent-1"), bucket_index_key("agent-1"));
+    }
+
+    #[test]
+    fn test_bucket_index_key_stays_within_shard_count() {
+        for identity in ["agent-1", "agent-2", "spiffe://cluster/agent"] {
+            let key = bucket_index_key(identity);
+            assert!(bucket_index_shard_keys().any(|shard_key| shard_key == key));
+        }
+    }
+
+    #[test]
+    fn test_retry_after_rounds_up() {
+        assert_eq!(retry_after_seconds(5.0, 2.0), 3);
+    }
+
+    #[test]
+    fn test_retry_after_handles_zero_rate() {
+        assert_eq!(retry_after_seconds(5.0, 0.0), 1);
+    }
+
+    #[test]
+    fn test_stale_identities_finds_expired_only() {
+        let mut index = HashMap::new();
+        index.insert("fresh".to_string(), 100.0);
+        index.insert("stale".to_string(), 0.0);
+        let stale = stale_identities(&index, BUCKET_TTL_SECONDS + 1.0);
+        assert_eq!(stale, vec!["stale".to_string()]);
+    }
+}
@@ -0,0 +1,161 @@
+// Server-Sent Event Parsing for Streaming A2A Responses
+// Incrementally reconstructs SSE frames out of response body chunks, since
+// a single event can straddle multiple `on_http_response_body` callbacks,
+// and extracts the MessagePart payloads each event carries so streamed
+// output can be token-counted and debited against the same budget as
+// requests.
+
+use crate::a2a::MessagePart;
+use serde::Deserialize;
+
+/// A single reassembled SSE event: every `data:` line in its frame, joined
+/// with `\n` per the SSE spec.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SseEvent {
+    pub data: String,
+}
+
+/// Buffers partial SSE frames across body chunks and yields complete events
+/// as they arrive.
+#[derive(Debug, Default)]
+pub struct SseParser {
+    buffer: String,
+}
+
+impl SseParser {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Feeds a chunk of response body bytes, returning the events it
+    /// completed. Bytes that don't yet form a full frame (no blank-line
+    /// terminator) are retained for the next call.
+    pub fn feed(&mut self, chunk: &[u8]) -> Vec<SseEvent> {
+        self.buffer.push_str(&String::from_utf8_lossy(chunk));
+
+        let mut events = Vec::new();
+        while let Some(pos) = self.buffer.find("\n\n") {
+            let frame: String = self.buffer.drain(..pos + 2).collect();
+            if let Some(event) = parse_frame(&frame) {
+                events.push(event);
+            }
+        }
+        events
+    }
+}
+
+fn parse_frame(frame: &str) -> Option<SseEvent> {
+    let lines: Vec<&str> = frame
+        .lines()
+        .filter_map(|line| {
+            let rest = line.strip_prefix("data:")?;
+            Some(rest.strip_prefix(' ').unwrap_or(rest))
+        })
+        .collect();
+
+    if lines.is_empty() {
+        return None;
+    }
+
+    Some(SseEvent { data: lines.join("\n") })
+}
+
+/// The shape of a streamed event's `data:` payload when it carries
+/// structured message parts, mirroring `MessageObject`.
+#[derive(Debug, Deserialize)]
+struct StreamedParts {
+    parts: Vec<MessagePart>,
+}
+
+/// Extracts the `MessagePart`s an SSE event's data payload carries: a
+/// `{"parts": [...]}` JSON object uses those parts directly, anything else
+/// (plain text, unparsable JSON) is treated as one bare text part.
+pub fn event_parts(event: &SseEvent) -> Vec<MessagePart> {
+    serde_json::from_str::<StreamedParts>(&event.data)
+        .map(|s| s.parts)
+        .unwrap_or_else(|_| vec![MessagePart::Text { text: event.data.clone() }])
+}
+
+/// Builds a terminal `event: error` SSE frame to inject into the stream
+/// when the response-token ceiling is exceeded, so the client learns why
+/// forwarding stopped instead of seeing a silently truncated stream.
+pub fn error_frame(message: &str) -> Vec<u8> {
+    format!("event: error\ndata: {{\"error\": \"{}\"}}\n\n", message).into_bytes()
+}
+
+/// Re-serializes an already-approved `SseEvent` back into a `data:` frame,
+/// so a chunk that's being cut off mid-stream can still forward the events
+/// in it that were already token-counted and debited, instead of the
+/// cutoff discarding bytes the caller was already charged for.
+pub fn reencode_event(event: &SseEvent) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    for line in event.data.split('\n') {
+        bytes.extend_from_slice(b"data: ");
+        bytes.extend_from_slice(line.as_bytes());
+        bytes.push(b'\n');
+    }
+    bytes.push(b'\n');
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_feed_yields_nothing_without_a_terminator() {
+        let mut parser = SseParser::new();
+        assert!(parser.feed(b"data: partial").is_empty());
+    }
+
+    #[test]
+    fn test_feed_yields_event_once_terminated() {
+        let mut parser = SseParser::new();
+        assert!(parser.feed(b"data: hel").is_empty());
+        let events = parser.feed(b"lo\n\n");
+        assert_eq!(events, vec![SseEvent { data: "hello".to_string() }]);
+    }
+
+    #[test]
+    fn test_feed_joins_multiple_data_lines() {
+        let mut parser = SseParser::new();
+        let events = parser.feed(b"data: line1\ndata: line2\n\n");
+        assert_eq!(events, vec![SseEvent { data: "line1\nline2".to_string() }]);
+    }
+
+    #[test]
+    fn test_feed_yields_multiple_events_in_one_chunk() {
+        let mut parser = SseParser::new();
+        let events = parser.feed(b"data: a\n\ndata: b\n\n");
+        assert_eq!(
+            events,
+            vec![SseEvent { data: "a".to_string() }, SseEvent { data: "b".to_string() }]
+        );
+    }
+
+    #[test]
+    fn test_event_parts_extracts_structured_parts() {
+        let event = SseEvent {
+            data: r#"{"parts": [{"type": "text", "text": "hi"}]}"#.to_string(),
+        };
+        let parts = event_parts(&event);
+        assert!(matches!(parts.as_slice(), [MessagePart::Text { text }] if text == "hi"));
+    }
+
+    #[test]
+    fn test_reencode_event_round_trips_through_feed() {
+        let mut parser = SseParser::new();
+        let events = parser.feed(b"data: line1\ndata: line2\n\n");
+        let reencoded = reencode_event(&events[0]);
+        assert_eq!(reencoded, b"data: line1\ndata: line2\n\n");
+    }
+
+    #[test]
+    fn test_event_parts_falls_back_to_bare_text() {
+        let event = SseEvent {
+            data: "just some text".to_string(),
+        };
+        let parts = event_parts(&event);
+        assert!(matches!(parts.as_slice(), [MessagePart::Text { text }] if text == "just some text"));
+    }
+}
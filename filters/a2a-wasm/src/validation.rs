@@ -3,17 +3,43 @@
 
 use crate::a2a::{A2ARequest, ValidationError};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Which token counting strategy produced a `ValidationResult`'s
+/// `estimated_tokens`, so observability headers can report it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TokenEstimationMethod {
+    /// `(chars + 3) / 4` approximation; used when no BPE vocabulary is configured.
+    Heuristic,
+    /// Real byte-pair-encoding merge simulation against a configured vocabulary.
+    Bpe,
+}
 
 /// ValidationResult contains the outcome of payload validation
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ValidationResult {
     pub valid: bool,
     pub estimated_tokens: u64,
+    pub estimation_method: TokenEstimationMethod,
+    /// Capabilities the request claimed, carried through so callers can
+    /// cross-check them against a discovered AgentCard without re-parsing
+    /// the payload.
+    pub capabilities: Vec<String>,
     pub errors: Vec<String>,
 }
 
-/// Validate an A2A JSON payload and estimate token count
-pub fn validate_a2a_payload(body: &str) -> Result<ValidationResult, String> {
+/// Validate an A2A JSON payload and estimate token count. When `bpe_vocab`
+/// is `Some` and holds at least one merge rule, token counts use the real
+/// BPE merge simulation; otherwise (including an empty vocab) they fall back
+/// to the char-based heuristic in `estimate_tokens`. Capabilities
+/// are checked against `allowed_capabilities`, which operators configure
+/// instead of it being a compile-time whitelist.
+pub fn validate_a2a_payload(
+    body: &str,
+    bpe_vocab: Option<&BpeVocab>,
+    allowed_capabilities: &[String],
+) -> Result<ValidationResult, String> {
     // Parse the request
     let request: A2ARequest = serde_json::from_str(body)
         .map_err(|e| ValidationError::InvalidJson(e.to_string()))?;
@@ -31,30 +57,36 @@ pub fn validate_a2a_payload(body: &str) -> Result<ValidationResult, String> {
 
     // Validate capabilities
     for cap in &request.capabilities {
-        if !is_valid_capability(cap) {
+        if !allowed_capabilities.iter().any(|allowed| allowed == cap) {
             errors.push(format!("invalid capability: {}", cap));
         }
     }
 
-    // Estimate token count
-    let estimated_tokens = estimate_tokens(&request);
+    // Estimate token count, preferring the real tokenizer when configured.
+    // A vocab that parsed to zero merge rules (malformed `bpe_merges`) is
+    // treated the same as no vocab at all, so a bad config degrades to the
+    // heuristic instead of silently counting ~1 token per character.
+    let (estimated_tokens, estimation_method) = match bpe_vocab.filter(|vocab| !vocab.is_empty()) {
+        Some(vocab) => (estimate_tokens_bpe(&request, vocab), TokenEstimationMethod::Bpe),
+        None => (estimate_tokens(&request), TokenEstimationMethod::Heuristic),
+    };
 
     Ok(ValidationResult {
         valid: errors.is_empty(),
         estimated_tokens,
+        estimation_method,
+        capabilities: request.capabilities.clone(),
         errors,
     })
 }
 
-fn is_valid_capability(cap: &str) -> bool {
-    const VALID_CAPS: &[&str] = &[
-        "a2a",
-        "streaming",
-        "pushNotifications",
-        "stateManagement",
-        "artifactSupport",
-    ];
-    VALID_CAPS.contains(&cap)
+/// The capability whitelist used when a filter config omits
+/// `allowed_capabilities`, matching the protocol's built-in capabilities.
+pub fn default_allowed_capabilities() -> Vec<String> {
+    ["a2a", "streaming", "pushNotifications", "stateManagement", "artifactSupport"]
+        .iter()
+        .map(|s| s.to_string())
+        .collect()
 }
 
 /// Estimate token count using character-based approximation
@@ -94,6 +126,193 @@ fn count_part_characters(part: &crate::a2a::MessagePart) -> u64 {
     }
 }
 
+/// A merge-rank table for byte-pair encoding: the lower the rank, the
+/// earlier that symbol pair is merged. Parsed from a GPT-2-style
+/// `merges.txt` blob (one `"left right"` pair per line, ordered by rank)
+/// supplied through the filter config, so no vocabulary ships in the crate
+/// itself.
+#[derive(Debug, Clone, Default)]
+pub struct BpeVocab {
+    ranks: HashMap<(String, String), u32>,
+}
+
+/// A pre-tokenized word longer than this many characters bypasses
+/// `BpeVocab::encode_word`'s merge simulation and falls back to the char/4
+/// heuristic instead, capping the per-word merge loop's cost.
+const MAX_BPE_WORD_CHARS: usize = 256;
+
+impl BpeVocab {
+    /// Parses a merges blob into a rank table. Blank lines and a leading
+    /// `#version` comment (as GPT-2's merges.txt has) are ignored.
+    pub fn from_merges(data: &str) -> Self {
+        let ranks = data
+            .lines()
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .enumerate()
+            .filter_map(|(rank, line)| {
+                let mut parts = line.split_whitespace();
+                let left = parts.next()?;
+                let right = parts.next()?;
+                Some(((left.to_string(), right.to_string()), rank as u32))
+            })
+            .collect();
+
+        BpeVocab { ranks }
+    }
+
+    pub fn len(&self) -> usize {
+        self.ranks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ranks.is_empty()
+    }
+
+    /// Encodes a single pre-tokenized word by repeatedly merging the
+    /// lowest-ranked adjacent symbol pair until no known pair remains,
+    /// returning the resulting symbol count. Words longer than
+    /// `MAX_BPE_WORD_CHARS` skip the merge simulation entirely and fall back
+    /// to the char/4 heuristic: `split_words` only breaks on
+    /// alphanumeric/punctuation transitions, so a long alphanumeric run
+    /// (e.g. a base64 blob inside a `Data`/`FunctionCall` part) becomes one
+    /// giant "word," and the merge loop below is O(word length squared)
+    /// with no other size guard on the hot request path.
+    fn encode_word(&self, word: &str) -> u64 {
+        let mut symbols: Vec<String> = word.chars().map(|c| c.to_string()).collect();
+
+        if symbols.len() > MAX_BPE_WORD_CHARS {
+            return (symbols.len() as u64 + 3) / 4;
+        }
+
+        loop {
+            let mut best: Option<(usize, u32)> = None;
+            for i in 0..symbols.len().saturating_sub(1) {
+                if let Some(&rank) = self.ranks.get(&(symbols[i].clone(), symbols[i + 1].clone())) {
+                    if best.map_or(true, |(_, best_rank)| rank < best_rank) {
+                        best = Some((i, rank));
+                    }
+                }
+            }
+
+            match best {
+                Some((i, _)) => {
+                    let merged = format!("{}{}", symbols[i], symbols[i + 1]);
+                    symbols.splice(i..=i + 1, [merged]);
+                }
+                None => break,
+            }
+        }
+
+        symbols.len() as u64
+    }
+
+    /// Encodes arbitrary text into a token count, pre-tokenizing on
+    /// whitespace/punctuation boundaries and caching per-word results since
+    /// agent payloads repeat the same tokens heavily.
+    pub fn encode(&self, text: &str, cache: &mut HashMap<String, u64>) -> u64 {
+        split_words(text)
+            .into_iter()
+            .map(|word| {
+                if let Some(&count) = cache.get(word) {
+                    return count;
+                }
+                let count = self.encode_word(word);
+                cache.insert(word.to_string(), count);
+                count
+            })
+            .sum()
+    }
+}
+
+/// Splits text into pre-token words on whitespace/punctuation boundaries,
+/// the same coarse split real BPE tokenizers pre-process with before
+/// running merges within each word.
+fn split_words(text: &str) -> Vec<&str> {
+    let mut words = Vec::new();
+    let mut start = None;
+    let mut in_word = false;
+
+    for (i, c) in text.char_indices() {
+        let is_word_char = c.is_alphanumeric();
+        match start {
+            None if !c.is_whitespace() => {
+                start = Some(i);
+                in_word = is_word_char;
+            }
+            Some(s) if c.is_whitespace() => {
+                words.push(&text[s..i]);
+                start = None;
+            }
+            Some(s) if is_word_char != in_word => {
+                words.push(&text[s..i]);
+                start = Some(i);
+                in_word = is_word_char;
+            }
+            _ => {}
+        }
+    }
+    if let Some(s) = start {
+        words.push(&text[s..]);
+    }
+
+    words
+}
+
+/// Estimates token count for an A2A request using a configured BPE
+/// vocabulary instead of the char/4 heuristic.
+pub fn estimate_tokens_bpe(request: &A2ARequest, vocab: &BpeVocab) -> u64 {
+    let mut cache = HashMap::new();
+    let mut tokens = vocab.encode(&request.task_id, &mut cache);
+
+    for part in &request.message_object.parts {
+        tokens += count_part_bpe_tokens(part, vocab, &mut cache);
+    }
+
+    tokens
+}
+
+fn count_part_bpe_tokens(
+    part: &crate::a2a::MessagePart,
+    vocab: &BpeVocab,
+    cache: &mut HashMap<String, u64>,
+) -> u64 {
+    match part {
+        crate::a2a::MessagePart::Text { text } => vocab.encode(text, cache),
+        crate::a2a::MessagePart::File { name, mime_type, .. } => {
+            vocab.encode(name, cache) + vocab.encode(mime_type, cache)
+        }
+        crate::a2a::MessagePart::Data { data } => serde_json::to_string(data)
+            .map(|s| vocab.encode(&s, cache))
+            .unwrap_or(0),
+        crate::a2a::MessagePart::FunctionCall { name, args, .. } => {
+            vocab.encode(name, cache)
+                + serde_json::to_string(args).map(|s| vocab.encode(&s, cache)).unwrap_or(0)
+        }
+        crate::a2a::MessagePart::FunctionResponse { call_id, response } => {
+            vocab.encode(call_id, cache)
+                + serde_json::to_string(response).map(|s| vocab.encode(&s, cache)).unwrap_or(0)
+        }
+    }
+}
+
+/// Estimates the token count of a bare list of message parts, the same way
+/// `estimate_tokens`/`estimate_tokens_bpe` do for a full request, but without
+/// a `task_id` to fold in. Used for SSE response events reassembled by
+/// `crate::sse`, which don't carry a whole `A2ARequest`.
+pub fn estimate_parts_tokens(
+    parts: &[crate::a2a::MessagePart],
+    bpe_vocab: Option<&BpeVocab>,
+    cache: &mut HashMap<String, u64>,
+) -> u64 {
+    match bpe_vocab.filter(|vocab| !vocab.is_empty()) {
+        Some(vocab) => parts.iter().map(|part| count_part_bpe_tokens(part, vocab, cache)).sum(),
+        None => {
+            let chars: u64 = parts.iter().map(count_part_characters).sum();
+            (chars + 3) / 4
+        }
+    }
+}
+
 /// Token bucket rate limiting calculation
 /// Returns (allowed, remaining_tokens)
 pub fn check_token_bucket(
@@ -165,4 +384,125 @@ mod tests {
         // "task-123" (8) + "Hello, world!" (13) = 21 chars ≈ 5-6 tokens
         assert!(tokens > 3 && tokens < 10);
     }
+
+    #[test]
+    fn test_bpe_vocab_parses_merges() {
+        let vocab = BpeVocab::from_merges("# version\nh e\nhe l\n");
+        assert_eq!(vocab.len(), 2);
+    }
+
+    #[test]
+    fn test_bpe_encode_merges_known_pairs() {
+        let vocab = BpeVocab::from_merges("h e\nhe l\nhel l\nhell o\n");
+        let mut cache = HashMap::new();
+        // Every pair in "hello" is known, so it should fully merge into one symbol.
+        assert_eq!(vocab.encode("hello", &mut cache), 1);
+    }
+
+    #[test]
+    fn test_bpe_encode_falls_back_per_character_without_merges() {
+        let vocab = BpeVocab::from_merges("");
+        let mut cache = HashMap::new();
+        assert_eq!(vocab.encode("hi", &mut cache), 2);
+    }
+
+    #[test]
+    fn test_bpe_encode_caches_repeated_words() {
+        let vocab = BpeVocab::from_merges("h e\nhe l\nhel l\nhell o\n");
+        let mut cache = HashMap::new();
+        vocab.encode("hello hello", &mut cache);
+        assert_eq!(cache.get("hello"), Some(&1));
+    }
+
+    #[test]
+    fn test_bpe_encode_word_falls_back_past_length_cap() {
+        let vocab = BpeVocab::from_merges("a a\n");
+        let mut cache = HashMap::new();
+        let blob: String = "a".repeat(MAX_BPE_WORD_CHARS + 1);
+        assert_eq!(vocab.encode(&blob, &mut cache), (blob.len() as u64 + 3) / 4);
+    }
+
+    #[test]
+    fn test_validate_a2a_payload_falls_back_to_heuristic_for_empty_vocab() {
+        let body = r#"{
+            "task_id": "task-123",
+            "message_object": {"role": "user", "parts": [{"type": "text", "text": "Hello, world!"}]},
+            "capabilities": ["a2a"]
+        }"#;
+
+        let empty_vocab = BpeVocab::from_merges("not-a-pair\nalso-not-a-pair\n");
+        assert!(empty_vocab.is_empty());
+
+        let result = validate_a2a_payload(body, Some(&empty_vocab), &default_allowed_capabilities()).unwrap();
+        assert_eq!(result.estimation_method, TokenEstimationMethod::Heuristic);
+        assert_eq!(result.estimated_tokens, estimate_tokens(&A2ARequest {
+            task_id: "task-123".to_string(),
+            message_object: crate::a2a::MessageObject {
+                role: "user".to_string(),
+                parts: vec![crate::a2a::MessagePart::Text { text: "Hello, world!".to_string() }],
+            },
+            capabilities: vec!["a2a".to_string()],
+            metadata: None,
+        }));
+    }
+
+    #[test]
+    fn test_estimate_parts_tokens_falls_back_to_heuristic_for_empty_vocab() {
+        let empty_vocab = BpeVocab::from_merges("");
+        let parts = vec![crate::a2a::MessagePart::Text { text: "Hello, world!".to_string() }];
+        let mut cache = HashMap::new();
+        assert_eq!(estimate_parts_tokens(&parts, Some(&empty_vocab), &mut cache), (13 + 3) / 4);
+    }
+
+    #[test]
+    fn test_split_words_separates_punctuation() {
+        assert_eq!(split_words("Hello, world!"), vec!["Hello", ",", "world", "!"]);
+    }
+
+    #[test]
+    fn test_validate_a2a_payload_rejects_capability_outside_allowlist() {
+        let body = r#"{
+            "task_id": "task-1",
+            "message_object": {"role": "user", "parts": [{"type": "text", "text": "hi"}]},
+            "capabilities": ["a2a", "notAllowed"]
+        }"#;
+
+        let result = validate_a2a_payload(body, None, &default_allowed_capabilities()).unwrap();
+        assert!(!result.valid);
+        assert!(result.errors.iter().any(|e| e.contains("notAllowed")));
+    }
+
+    #[test]
+    fn test_estimate_parts_tokens_heuristic_matches_full_request() {
+        let parts = vec![crate::a2a::MessagePart::Text {
+            text: "Hello, world!".to_string(),
+        }];
+        let mut cache = HashMap::new();
+        // No task_id folded in here, so this is strictly the "Hello, world!" chars.
+        assert_eq!(estimate_parts_tokens(&parts, None, &mut cache), (13 + 3) / 4);
+    }
+
+    #[test]
+    fn test_estimate_parts_tokens_bpe_sums_per_part() {
+        let vocab = BpeVocab::from_merges("h e\nhe l\nhel l\nhell o\n");
+        let parts = vec![
+            crate::a2a::MessagePart::Text { text: "hello".to_string() },
+            crate::a2a::MessagePart::Text { text: "hello".to_string() },
+        ];
+        let mut cache = HashMap::new();
+        assert_eq!(estimate_parts_tokens(&parts, Some(&vocab), &mut cache), 2);
+    }
+
+    #[test]
+    fn test_validate_a2a_payload_honors_custom_allowlist() {
+        let body = r#"{
+            "task_id": "task-1",
+            "message_object": {"role": "user", "parts": [{"type": "text", "text": "hi"}]},
+            "capabilities": ["customCap"]
+        }"#;
+
+        let allowed = vec!["customCap".to_string()];
+        let result = validate_a2a_payload(body, None, &allowed).unwrap();
+        assert!(result.valid);
+    }
 }